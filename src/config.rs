@@ -1,4 +1,4 @@
-use chrono::Duration;
+use chrono::{DateTime, Datelike, Duration, Local, Timelike};
 use serde::de::Visitor;
 use serde::{de::Error, Deserialize, Deserializer};
 use std::fmt;
@@ -9,13 +9,290 @@ pub struct Config {
     /// The folder from which to reap
     pub path: PathBuf,
 
-    /// Whether to treat the files as btrfs subvolumes
+    /// How to get rid of an expired entry. Defaults to removing it outright.
     #[serde(default)]
-    pub btrfs: bool,
+    pub delete_method: DeleteMethod,
+
+    #[serde(default)]
+    pub periods: Vec<ConfPeriod>,
+
+    /// Proxmox-style keep-last/hourly/daily/weekly/monthly/yearly retention,
+    /// used as an alternative to (or alongside) `periods`.
+    #[serde(default)]
+    pub gfs: Option<ConfGfs>,
+
+    /// Where to get each entry's timestamp from. Defaults to parsing the
+    /// entry's name as RFC3339.
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+
+    /// Once the period/GFS keep set is computed, additionally delete the
+    /// oldest kept entries until the directory's total size drops under
+    /// this budget, e.g. "50G".
+    #[serde(default, deserialize_with = "parse_optional_size")]
+    pub max_total_size: Option<u64>,
+
+    /// Independent backup series sharing this directory, each retained with
+    /// its own `periods`. When non-empty, entries are matched against each
+    /// group's `pattern` instead of applying `periods`/`gfs` directly.
+    #[serde(default)]
+    pub groups: Vec<ConfGroup>,
+
+    /// How often to reap under `--watch`. Without `--watch`, this is
+    /// ignored and filereap runs once.
+    #[serde(default)]
+    pub schedule: Option<ConfSchedule>,
+
+    /// Number of threads to use for parallel deletion. Defaults to rayon's
+    /// automatic choice (the number of CPUs). Directory scanning always
+    /// runs on rayon's global thread pool and is not affected by this.
+    #[serde(default)]
+    pub parallelism: Option<usize>,
+}
+
+impl Config {
+    /// Check for combinations that each parse fine individually but don't
+    /// make sense together.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.groups.is_empty() && self.gfs.is_some() {
+            return Err(
+                "top-level `gfs` has no effect when `groups` is set; give each group its own `periods` instead".to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Controls how `--watch` schedules repeated reap passes.
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfSchedule {
+    /// Reap every this often, e.g. "1h".
+    #[serde(default, deserialize_with = "parse_optional_interval")]
+    pub interval: Option<SimpleDuration>,
+
+    /// A cron-style "minute hour day month weekday" spec, e.g. "0 3 * * *".
+    /// Each field is `*` or a comma-separated list of values.
+    #[serde(default, deserialize_with = "parse_optional_cron")]
+    pub cron: Option<TimeSpec>,
+
+    /// Also trigger an immediate reap pass when entries appear in `path`,
+    /// in between scheduled runs.
+    #[serde(default)]
+    pub watch_directory: bool,
+}
+
+/// A parsed cron-style "minute hour day month weekday" spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSpec {
+    pub minute: FieldSpec,
+    pub hour: FieldSpec,
+    pub day: FieldSpec,
+    pub month: FieldSpec,
+    pub weekday: FieldSpec,
+}
+
+/// A single cron field: either `*` or a set of values to match exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldSpec {
+    Any,
+    Values(Vec<u32>),
+}
+
+impl FieldSpec {
+    /// Parse a single field, rejecting any value outside `min..=max`.
+    fn parse(s: &str, min: u32, max: u32) -> Result<Self, String> {
+        if s == "*" {
+            return Ok(FieldSpec::Any);
+        }
+
+        let values: Vec<u32> = s
+            .split(',')
+            .map(|v| {
+                v.trim()
+                    .parse()
+                    .map_err(|e| format!("invalid cron field value {v:?}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        for &value in &values {
+            if value < min || value > max {
+                return Err(format!(
+                    "cron field value {value} out of range {min}-{max}"
+                ));
+            }
+        }
+
+        Ok(FieldSpec::Values(values))
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        match self {
+            FieldSpec::Any => true,
+            FieldSpec::Values(values) => values.contains(&value),
+        }
+    }
+}
+
+impl TimeSpec {
+    fn parse(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "cron spec must have 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+
+        let spec = TimeSpec {
+            minute: FieldSpec::parse(minute, 0, 59)?,
+            hour: FieldSpec::parse(hour, 0, 23)?,
+            day: FieldSpec::parse(day, 1, 31)?,
+            month: FieldSpec::parse(month, 1, 12)?,
+            weekday: FieldSpec::parse(weekday, 0, 6)?,
+        };
+
+        if !spec.is_feasible() {
+            return Err("cron spec can never match, e.g. day 31 combined with a month that doesn't have one".to_string());
+        }
+
+        Ok(spec)
+    }
+
+    /// Whether `day` and `month` can ever coincide, i.e. at least one
+    /// requested day falls within at least one requested month.
+    fn is_feasible(&self) -> bool {
+        let FieldSpec::Values(days) = &self.day else {
+            return true;
+        };
+        let FieldSpec::Values(months) = &self.month else {
+            return true;
+        };
+
+        let max_day_in_month = |month: u32| match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 => 29, // allow Feb 29th in case of a leap year
+            _ => 0,
+        };
+
+        days.iter()
+            .any(|&day| months.iter().any(|&month| day <= max_day_in_month(month)))
+    }
+
+    pub fn matches(&self, time: DateTime<Local>) -> bool {
+        self.minute.matches(time.minute())
+            && self.hour.matches(time.hour())
+            && self.day.matches(time.day())
+            && self.month.matches(time.month())
+            && self.weekday.matches(time.weekday().num_days_from_sunday())
+    }
+
+    /// The next minute-aligned time, strictly after `from`, that matches
+    /// this spec. Returns `None` if no match turns up within 4 years. This
+    /// can happen for a spec that passed [TimeSpec::parse]'s validation,
+    /// which doesn't take `weekday` into account (e.g. Feb 29th on a
+    /// Monday); the search is bounded since it would otherwise spin forever.
+    pub fn next_after(&self, from: DateTime<Local>) -> Option<DateTime<Local>> {
+        const MAX_MINUTES_CHECKED: i64 = 4 * 366 * 24 * 60;
+
+        let mut candidate = from + Duration::minutes(1);
+        candidate -= Duration::seconds(candidate.second().into());
+        candidate -= Duration::nanoseconds(candidate.nanosecond().into());
+
+        for _ in 0..MAX_MINUTES_CHECKED {
+            if self.matches(candidate) {
+                return Some(candidate);
+            }
+            candidate += Duration::minutes(1);
+        }
+
+        None
+    }
+}
+
+/// One named backup series within a shared directory.
+#[derive(Debug, Deserialize)]
+pub struct ConfGroup {
+    /// Used only in log output to identify the group.
+    pub name: String,
+
+    /// A literal prefix (e.g. `"db-"`), or a regex, matched against the
+    /// start of each entry's name to decide whether it belongs to this
+    /// group. A regex with a named `time` capture group uses that capture
+    /// as the timestamp portion of the name; otherwise the text following
+    /// the prefix/match is used, parsed via the top-level
+    /// `timestamp_source`.
+    pub pattern: String,
 
     pub periods: Vec<ConfPeriod>,
 }
 
+/// How to get rid of an expired entry.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMethod {
+    /// `rm`/`rm -r` the entry.
+    #[default]
+    Remove,
+
+    /// `btrfs subvolume delete` the entry.
+    BtrfsSubvolume,
+
+    /// Move the entry into this archive directory instead of destroying it.
+    MoveTo(PathBuf),
+
+    /// Run this program (with the entry's path appended as the final
+    /// argument) instead of deleting anything directly.
+    Command(Vec<String>),
+}
+
+/// How to determine the timestamp of a directory entry.
+#[derive(Debug, Default, Deserialize, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// Parse the entry's name as an RFC3339 timestamp (the original, default
+    /// behavior).
+    #[default]
+    Rfc3339,
+
+    /// Use the entry's filesystem modification time.
+    Mtime,
+
+    /// Extract the timestamp from the entry's name using either a chrono
+    /// `strptime`-style format string, or a regex with a named `time`
+    /// capture group.
+    Pattern(String),
+}
+
+/// Grandfather-father-son style retention: keep a fixed number of the newest
+/// files in each of several bucket granularities.
+///
+/// Each field is the number of distinct buckets of that granularity to
+/// retain, counting from the newest file backwards. A field left unset (or
+/// set to 0) disables that category. `keep_last` is special: it counts
+/// files directly rather than buckets.
+#[derive(Debug, Default, Deserialize, Hash, PartialEq, Eq)]
+pub struct ConfGfs {
+    #[serde(default)]
+    pub keep_last: Option<u32>,
+
+    #[serde(default)]
+    pub keep_hourly: Option<u32>,
+
+    #[serde(default)]
+    pub keep_daily: Option<u32>,
+
+    #[serde(default)]
+    pub keep_weekly: Option<u32>,
+
+    #[serde(default)]
+    pub keep_monthly: Option<u32>,
+
+    #[serde(default)]
+    pub keep_yearly: Option<u32>,
+}
+
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum SimpleDuration {
     Weeks(i64),
@@ -62,28 +339,41 @@ where
     D: Deserializer<'de>,
 {
     let s = d.deserialize_str(StrVisitor)?;
+    parse_duration_str(s).map_err(D::Error::custom)
+}
+
+/// Deserialize an optional [SimpleDuration], e.g. for a field that's absent
+/// unless configured.
+fn parse_optional_interval<'de, D>(d: D) -> Result<Option<SimpleDuration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Option::deserialize(d)?;
+    s.map(|s| parse_duration_str(&s).map_err(D::Error::custom))
+        .transpose()
+}
+
+fn parse_duration_str(s: &str) -> Result<SimpleDuration, String> {
     let s = s.trim();
 
     if s.contains(char::is_whitespace) {
-        return Err(D::Error::custom("duration can't include whitespace"));
+        return Err("duration can't include whitespace".to_string());
     }
 
     let suffix = s
         .chars()
         .next_back()
-        .ok_or_else(|| D::Error::custom("duration can't be empty"))?;
+        .ok_or_else(|| "duration can't be empty".to_string())?;
 
     if suffix.is_ascii_digit() {
-        return Err(D::Error::custom(
-            r#"specify duration with a suffix, i.e. "24h""#,
-        ));
+        return Err(r#"specify duration with a suffix, i.e. "24h""#.to_string());
     }
 
     let value = &s[..s.len() - suffix.len_utf8()];
 
     let value: u64 = value
         .parse()
-        .map_err(|e| D::Error::custom(format!("failed to parse duration value: {e}")))?;
+        .map_err(|e| format!("failed to parse duration value: {e}"))?;
     let value = value as i64;
 
     use SimpleDuration::*;
@@ -93,7 +383,86 @@ where
         'h' => Hours(value),
         'd' => Days(value),
         'w' => Weeks(value),
-        d => return Err(D::Error::custom(format!("unknown unit of duration: {d:?}"))),
+        d => return Err(format!("unknown unit of duration: {d:?}")),
+    })
+}
+
+/// Deserialize an optional [TimeSpec] cron string.
+fn parse_optional_cron<'de, D>(d: D) -> Result<Option<TimeSpec>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Option<String> = Option::deserialize(d)?;
+    s.map(|s| TimeSpec::parse(&s).map_err(D::Error::custom))
+        .transpose()
+}
+
+/// Deserialize an optional human-readable size like "50G" into a byte count.
+fn parse_optional_size<'de, D>(d: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct OptSizeVisitor;
+
+    impl<'de> Visitor<'de> for OptSizeVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str(r#"an optional size string, e.g. "50G""#)
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_some<D2>(self, d: D2) -> Result<Self::Value, D2::Error>
+        where
+            D2: Deserializer<'de>,
+        {
+            parse_simple_size(d).map(Some)
+        }
+    }
+
+    d.deserialize_option(OptSizeVisitor)
+}
+
+/// Deserialize a byte count from a string like "50G" or "512M".
+fn parse_simple_size<'de, D>(d: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = d.deserialize_str(StrVisitor)?;
+    let s = s.trim();
+
+    if s.contains(char::is_whitespace) {
+        return Err(D::Error::custom("size can't include whitespace"));
+    }
+
+    let suffix = s
+        .chars()
+        .next_back()
+        .ok_or_else(|| D::Error::custom("size can't be empty"))?;
+
+    if suffix.is_ascii_digit() {
+        return Err(D::Error::custom(r#"specify size with a suffix, i.e. "50G""#));
+    }
+
+    let value = &s[..s.len() - suffix.len_utf8()];
+
+    let value: u64 = value
+        .parse()
+        .map_err(|e| D::Error::custom(format!("failed to parse size value: {e}")))?;
+
+    Ok(match suffix.to_ascii_lowercase() {
+        'b' => value,
+        'k' => value * 1024,
+        'm' => value * 1024u64.pow(2),
+        'g' => value * 1024u64.pow(3),
+        't' => value * 1024u64.pow(4),
+        d => return Err(D::Error::custom(format!("unknown unit of size: {d:?}"))),
     })
 }
 
@@ -113,3 +482,52 @@ impl<'de> Visitor<'de> for StrVisitor {
         Ok(s)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn field_spec_rejects_out_of_range_values() {
+        let tests = [
+            ("60", 0, 59), // minute
+            ("24", 0, 23), // hour
+            ("0", 1, 31),  // day
+            ("13", 1, 12), // month
+            ("7", 0, 6),   // weekday
+        ];
+
+        for (value, min, max) in tests {
+            assert!(
+                FieldSpec::parse(value, min, max).is_err(),
+                "{value:?} should be out of range {min}-{max}"
+            );
+        }
+
+        assert!(FieldSpec::parse("30", 0, 59).is_ok());
+        assert!(FieldSpec::parse("*", 0, 59).is_ok());
+    }
+
+    #[test]
+    fn time_spec_rejects_impossible_day_month_combos() {
+        // the 31st never falls in February
+        assert!(TimeSpec::parse("0 0 31 2 *").is_err());
+        // but it's fine alongside a month that has one
+        assert!(TimeSpec::parse("0 0 31 1,2 *").is_ok());
+    }
+
+    #[test]
+    fn time_spec_next_after_skips_non_matching_minutes() {
+        // every day at 03:30
+        let spec = TimeSpec::parse("30 3 * * *").unwrap();
+
+        let from = DateTime::parse_from_rfc3339("2020-01-01T00:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let next = spec.next_after(from).expect("should find a match");
+
+        assert_eq!(next.hour(), 3);
+        assert_eq!(next.minute(), 30);
+        assert_eq!(next.date_naive(), from.date_naive());
+    }
+}
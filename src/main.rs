@@ -6,17 +6,58 @@ mod config;
 #[macro_use]
 extern crate log;
 
-use chrono::{DateTime, Duration, FixedOffset, Local};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
 use clap::{ArgAction, Parser};
-use config::{ConfPeriod, Config, SimpleDuration};
+use config::{ConfGfs, ConfPeriod, Config, DeleteMethod, SimpleDuration, TimestampSource};
 use eyre::{eyre, Context};
 use log::LevelFilter;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use notify::Watcher;
+use rayon::prelude::*;
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 
 type FileName = DateTime<FixedOffset>;
 
+/// A directory entry paired with its resolved timestamp.
+///
+/// Kept separate from [FileName] because the timestamp no longer always
+/// round-trips to the entry's path (e.g. under [TimestampSource::Mtime] or
+/// [TimestampSource::Pattern]), so the real path has to be carried alongside
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct DatedFile {
+    time: FileName,
+    path: PathBuf,
+    /// Size in bytes, summed over the whole tree if this entry is a
+    /// directory (e.g. a btrfs subvolume) and `max_total_size` is set. A
+    /// directory's size is 0 when `max_total_size` is not set.
+    size: u64,
+}
+
+impl fmt::Display for DatedFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.path.display(), self.time)
+    }
+}
+
+impl PartialOrd for DatedFile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DatedFile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.time.cmp(&other.time)
+    }
+}
+
 #[derive(Parser)]
 #[command(version)]
 struct Opt {
@@ -33,6 +74,10 @@ struct Opt {
     /// Do not delete anything
     #[clap(long, short)]
     dry_run: bool,
+
+    /// Run continuously instead of once, per the config's `schedule`
+    #[clap(long)]
+    watch: bool,
 }
 
 fn main() -> eyre::Result<()> {
@@ -58,7 +103,106 @@ fn run(opt: &Opt) -> eyre::Result<()> {
         .wrap_err_with(|| format!("Failed to read config file {:?}", opt.config))?;
 
     let config: Config = toml::from_str(&config).wrap_err("Failed to parse config file")?;
+    config.validate().map_err(|err| eyre!("Invalid config: {err}"))?;
+
+    if opt.watch {
+        watch_loop(&config, opt)
+    } else {
+        reap_once(&config, opt)
+    }
+}
+
+/// Sleep until the next scheduled run (and/or directory change), then reap,
+/// forever.
+fn watch_loop(config: &Config, opt: &Opt) -> eyre::Result<()> {
+    let schedule = config
+        .schedule
+        .as_ref()
+        .ok_or_else(|| eyre!("--watch requires a [schedule] section in the config"))?;
+
+    let watcher = schedule
+        .watch_directory
+        .then(|| watch_directory(&config.path))
+        .transpose()?;
+
+    loop {
+        let next_fire = [
+            schedule.interval.map(|interval| Local::now() + Duration::from(interval)),
+            schedule.cron.as_ref().and_then(|cron| cron.next_after(Local::now())),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+
+        match (next_fire, &watcher) {
+            (Some(next_fire), Some((_watcher, rx))) => loop {
+                let timeout = (next_fire - Local::now())
+                    .to_std()
+                    .unwrap_or(std::time::Duration::ZERO);
+                match rx.recv_timeout(timeout) {
+                    Ok(Ok(_event)) => {
+                        info!("detected a change in {:?}, waking up", config.path);
+                        break;
+                    }
+                    Ok(Err(err)) => warn!("directory watcher reported an error: {err}"),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        info!("waking up on schedule");
+                        break;
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        return Err(eyre!("directory watcher disconnected"))
+                    }
+                }
+            },
+            (Some(next_fire), None) => {
+                let sleep_for = (next_fire - Local::now())
+                    .to_std()
+                    .unwrap_or(std::time::Duration::ZERO);
+                std::thread::sleep(sleep_for);
+                info!("waking up on schedule");
+            }
+            (None, Some((_watcher, rx))) => loop {
+                match rx.recv() {
+                    Ok(Ok(_event)) => {
+                        info!("detected a change in {:?}, waking up", config.path);
+                        break;
+                    }
+                    Ok(Err(err)) => warn!("directory watcher reported an error: {err}"),
+                    Err(_) => return Err(eyre!("directory watcher disconnected")),
+                }
+            },
+            (None, None) => {
+                return Err(eyre!(
+                    "schedule must set `interval`, `cron`, or `watch_directory`"
+                ));
+            }
+        }
+
+        if let Err(err) = reap_once(config, opt) {
+            warn!("reap pass failed, will retry at the next scheduled run: {err:#}");
+        }
+    }
+}
 
+/// Watch `path` for changes, returning the watcher (which must be kept
+/// alive for as long as events are wanted) and a channel of its events.
+fn watch_directory(
+    path: &Path,
+) -> eyre::Result<(
+    notify::RecommendedWatcher,
+    mpsc::Receiver<notify::Result<notify::Event>>,
+)> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(move |res| { let _ = tx.send(res); })
+            .wrap_err("failed to create directory watcher")?;
+    watcher
+        .watch(path, notify::RecursiveMode::NonRecursive)
+        .wrap_err_with(|| format!("failed to watch directory {path:?}"))?;
+    Ok((watcher, rx))
+}
+
+fn reap_once(config: &Config, opt: &Opt) -> eyre::Result<()> {
     debug!("periods:");
     for period in &config.periods {
         debug!(
@@ -69,49 +213,326 @@ fn run(opt: &Opt) -> eyre::Result<()> {
 
     info!("scanning directory {:?}", config.path);
 
-    let mut files = BinaryHeap::new();
+    let group_patterns: Vec<GroupPattern> = config
+        .groups
+        .iter()
+        .map(|group| GroupPattern::compile(&group.pattern))
+        .collect();
 
     let dir_err = || format!("Failed to read directory {:?}", config.path);
-
-    for entry in fs::read_dir(&config.path).wrap_err_with(dir_err)? {
-        let name = entry.wrap_err_with(dir_err)?.file_name();
-        let name = name.to_string_lossy();
-        if let Ok(time) = DateTime::parse_from_rfc3339(&name) {
-            trace!("found \"{name}\"");
-            files.push(time);
-        } else {
-            trace!("ignoring \"{name}\", couldn't parse filename as rfc3339");
+    let entries: Vec<fs::DirEntry> = fs::read_dir(&config.path)
+        .wrap_err_with(dir_err)?
+        .collect::<std::io::Result<_>>()
+        .wrap_err_with(dir_err)?;
+
+    let scanned: Vec<ScannedFile> = entries
+        .into_par_iter()
+        .filter_map(|entry| scan_entry(config, &group_patterns, &entry))
+        .collect();
+
+    let mut files = Vec::with_capacity(scanned.len());
+    let mut group_files: HashMap<usize, Vec<DatedFile>> = HashMap::new();
+    for scanned in scanned {
+        if let Some(group) = scanned.group {
+            group_files.entry(group).or_default().push(scanned.file.clone());
         }
+        files.push(scanned.file);
     }
-    let files = files.into_sorted_vec();
+    files.sort();
 
     let now = Local::now();
-    let keep_files = check_files_to_keep(now, &config.periods, &files);
+    let mut keep_files = HashSet::new();
+    if config.groups.is_empty() {
+        keep_files.extend(check_files_to_keep(now, &config.periods, &files));
+        if let Some(gfs) = &config.gfs {
+            keep_files.extend(check_files_to_keep_gfs(gfs, &files));
+        }
+    } else {
+        for (index, group) in config.groups.iter().enumerate() {
+            let mut group_list = group_files.remove(&index).unwrap_or_default();
+            group_list.sort();
+            keep_files.extend(check_files_to_keep(now, &group.periods, &group_list));
+        }
+    }
+    if let Some(max_total_size) = config.max_total_size {
+        trim_to_size_budget(&mut keep_files, &files, max_total_size);
+    }
 
     info!("final decision:");
-    for &file in &files {
-        let keep_file = keep_files.contains(&file);
-
-        if keep_file {
+    for file in &files {
+        if keep_files.contains(file) {
             debug!("  {file} KEEP");
         } else {
             info!("  {file} DELETE");
-            if opt.dry_run {
-                debug!("dry run enabled, file not deleted");
-            } else {
-                delete_file(&config, file)?;
+        }
+    }
+
+    let to_delete: Vec<&DatedFile> = files.iter().filter(|file| !keep_files.contains(*file)).collect();
+
+    let pool = {
+        let mut builder = rayon::ThreadPoolBuilder::new();
+        if let Some(threads) = config.parallelism {
+            builder = builder.num_threads(threads);
+        }
+        builder
+            .build()
+            .wrap_err("failed to build deletion thread pool")?
+    };
+
+    let results: Vec<(&DatedFile, eyre::Result<()>)> = pool.install(|| {
+        to_delete
+            .into_par_iter()
+            .map(|file| {
+                let result = if opt.dry_run {
+                    debug!("dry run enabled, {file} not deleted");
+                    Ok(())
+                } else {
+                    delete_file(config, file)
+                };
+                (file, result)
+            })
+            .collect()
+    });
+
+    let mut deleted_files = 0u64;
+    let mut freed_bytes = 0u64;
+    let mut failures = Vec::new();
+
+    for (file, result) in results {
+        match result {
+            Ok(()) => {
+                deleted_files += 1;
+                freed_bytes += file.size;
             }
+            Err(err) => failures.push((file, err)),
         }
     }
 
+    // `MoveTo`/`Command` don't necessarily reclaim any space (they relocate
+    // or hand off the entry), so only call it "freed" for the methods that
+    // actually delete.
+    let verb = match (&config.delete_method, opt.dry_run) {
+        (DeleteMethod::Remove | DeleteMethod::BtrfsSubvolume, false) => "freed",
+        (DeleteMethod::Remove | DeleteMethod::BtrfsSubvolume, true) => "would free",
+        (DeleteMethod::MoveTo(_) | DeleteMethod::Command(_), false) => "processed",
+        (DeleteMethod::MoveTo(_) | DeleteMethod::Command(_), true) => "would process",
+    };
+
+    info!(
+        "{verb} {} across {} files",
+        human_size(freed_bytes),
+        deleted_files
+    );
+
+    if !failures.is_empty() {
+        const MAX_SHOWN: usize = 5;
+        warn!("{} of {} deletions failed:", failures.len(), deleted_files + failures.len() as u64);
+        for (file, err) in failures.iter().take(MAX_SHOWN) {
+            warn!("  {file}: {err:#}");
+        }
+        if failures.len() > MAX_SHOWN {
+            warn!("  ... and {} more", failures.len() - MAX_SHOWN);
+        }
+        return Err(eyre!("{} deletions failed", failures.len()));
+    }
+
     Ok(())
 }
 
+struct ScannedFile {
+    file: DatedFile,
+    group: Option<usize>,
+}
+
+/// Parse a single directory entry's timestamp and size, matching it against
+/// `group_patterns` (compiled once per reap pass from `config.groups`) if
+/// any are configured. Returns `None` for entries that should be ignored (no
+/// timestamp, or no matching group).
+fn scan_entry(config: &Config, group_patterns: &[GroupPattern], entry: &fs::DirEntry) -> Option<ScannedFile> {
+    let name = entry.file_name();
+    let name = name.to_string_lossy();
+
+    if group_patterns.is_empty() {
+        let time = parse_timestamp(&config.timestamp_source, &name, &entry.path()).or_else(|| {
+            trace!("ignoring \"{name}\", couldn't determine timestamp");
+            None
+        })?;
+        trace!("found \"{name}\"");
+        let path = entry.path();
+        let size = entry_size(config, &path);
+        return Some(ScannedFile {
+            file: DatedFile { time, path, size },
+            group: None,
+        });
+    }
+
+    let (group_index, rest) = find_group(group_patterns, &name).or_else(|| {
+        trace!("ignoring \"{name}\", no group matched");
+        None
+    })?;
+
+    let time = parse_timestamp(&config.timestamp_source, &rest, &entry.path()).or_else(|| {
+        trace!("ignoring \"{name}\", couldn't determine timestamp");
+        None
+    })?;
+
+    trace!("found \"{name}\" in group {:?}", config.groups[group_index].name);
+    let path = entry.path();
+    let size = entry_size(config, &path);
+    Some(ScannedFile {
+        file: DatedFile { time, path, size },
+        group: Some(group_index),
+    })
+}
+
+/// The size in bytes of a directory entry. A plain file's size is cheap to
+/// get and always computed; a directory's size requires recursing the whole
+/// tree (e.g. for a btrfs subvolume), which is only worth doing when
+/// `config.max_total_size` is actually set to act on it.
+fn entry_size(config: &Config, path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            warn!("failed to determine size of {path:?}: {err}");
+            return 0;
+        }
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    if config.max_total_size.is_none() {
+        return 0;
+    }
+
+    match dir_size(path) {
+        Ok(size) => size,
+        Err(err) => {
+            warn!("failed to determine size of {path:?}: {err}");
+            0
+        }
+    }
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += dir_size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Remove the oldest entries from `keep_files` until their total size is at
+/// or under `max_total_size`.
+fn trim_to_size_budget(keep_files: &mut HashSet<DatedFile>, files: &[DatedFile], max_total_size: u64) {
+    let kept: Vec<&DatedFile> = files.iter().filter(|file| keep_files.contains(*file)).collect();
+    let mut total_size: u64 = kept.iter().map(|file| file.size).sum();
+
+    for file in kept {
+        if total_size <= max_total_size {
+            break;
+        }
+        total_size -= file.size;
+        keep_files.remove(file);
+    }
+}
+
+/// Format a byte count in human-readable binary units, e.g. "4.2 GiB".
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Resolve the timestamp of a directory entry according to the configured
+/// [TimestampSource]. Returns `None` if the entry doesn't match.
+fn parse_timestamp(source: &TimestampSource, name: &str, path: &Path) -> Option<FileName> {
+    match source {
+        TimestampSource::Rfc3339 => DateTime::parse_from_rfc3339(name).ok(),
+        TimestampSource::Mtime => {
+            let modified = fs::metadata(path).ok()?.modified().ok()?;
+            Some(DateTime::<Local>::from(modified).fixed_offset())
+        }
+        TimestampSource::Pattern(pattern) => parse_with_pattern(pattern, name),
+    }
+}
+
+fn parse_with_pattern(pattern: &str, name: &str) -> Option<FileName> {
+    if let Ok(naive) = NaiveDateTime::parse_from_str(name, pattern) {
+        return Local.from_local_datetime(&naive).single().map(|t| t.fixed_offset());
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(name, pattern) {
+        let naive = date.and_hms_opt(0, 0, 0)?;
+        return Local.from_local_datetime(&naive).single().map(|t| t.fixed_offset());
+    }
+
+    let re = Regex::new(pattern).ok()?;
+    let time_str = re.captures(name)?.name("time")?.as_str();
+    DateTime::parse_from_rfc3339(time_str).ok()
+}
+
+/// A group's `pattern`, compiled once (instead of per scanned entry) and
+/// anchored so it only ever matches from the start of the name.
+enum GroupPattern {
+    /// A regex, anchored with `^(?:...)` so it can't match in the middle of
+    /// an unrelated name. A named `time` capture gives the timestamp
+    /// portion directly; otherwise the text after the whole match is used.
+    Regex(Regex),
+
+    /// The pattern doesn't compile as a regex; treated as a literal prefix.
+    Literal(String),
+}
+
+impl GroupPattern {
+    fn compile(pattern: &str) -> Self {
+        match Regex::new(&format!("^(?:{pattern})")) {
+            Ok(re) => GroupPattern::Regex(re),
+            Err(_) => GroupPattern::Literal(pattern.to_string()),
+        }
+    }
+
+    fn match_name<'a>(&self, name: &'a str) -> Option<Cow<'a, str>> {
+        match self {
+            GroupPattern::Regex(re) => {
+                let caps = re.captures(name)?;
+                match caps.name("time") {
+                    Some(time) => Some(Cow::Borrowed(time.as_str())),
+                    None => Some(Cow::Borrowed(&name[caps.get(0)?.end()..])),
+                }
+            }
+            GroupPattern::Literal(prefix) => Some(Cow::Borrowed(name.strip_prefix(prefix.as_str())?)),
+        }
+    }
+}
+
+/// Find the first group whose pattern matches `name`, returning its index
+/// and the portion of the name to derive the timestamp from.
+fn find_group<'a>(group_patterns: &[GroupPattern], name: &'a str) -> Option<(usize, Cow<'a, str>)> {
+    group_patterns
+        .iter()
+        .enumerate()
+        .find_map(|(index, pattern)| Some((index, pattern.match_name(name)?)))
+}
+
 fn check_files_to_keep(
     now: DateTime<Local>,
     periods: &[ConfPeriod],
-    files: &[FileName],
-) -> HashSet<FileName> {
+    files: &[DatedFile],
+) -> HashSet<DatedFile> {
     let mut files = files.to_vec();
 
     debug_assert_eq!(
@@ -143,7 +564,7 @@ fn check_files_to_keep(
                 None => break 'period,
             };
 
-            let file_chunk = ChunkTime::of(period, file.into());
+            let file_chunk = ChunkTime::of(period, file.time.into());
 
             let index = file_chunk.index();
 
@@ -154,8 +575,8 @@ fn check_files_to_keep(
 
             if index <= stop_index {
                 trace!("  not in this period, checking next");
+                cursor = file.time.into();
                 files.push(file);
-                cursor = file.into();
                 break 'chunk;
             }
 
@@ -164,39 +585,135 @@ fn check_files_to_keep(
         }
     }
 
-    chunked_files.values().copied().collect()
+    chunked_files.into_values().collect()
 }
 
-fn delete_file(config: &Config, file: FileName) -> eyre::Result<()> {
-    let file_path = config.path.join(file.to_rfc3339());
+/// A GFS category's configured bucket count, and the function computing a
+/// file's bucket key for that category (e.g. `%Y%m%d` for daily).
+type GfsCategory = (Option<u32>, fn(&FileName) -> String);
+
+/// Compute the set of files to keep under Proxmox-style GFS retention.
+///
+/// Files are walked newest-first; for each enabled category a bucket key is
+/// computed (e.g. `%Y%m%d` for daily) and the first file seen in each
+/// distinct bucket is kept, until the category's configured count of
+/// distinct buckets has been retained. `keep_last` counts files rather than
+/// buckets. A file is kept if any category selects it.
+fn check_files_to_keep_gfs(gfs: &ConfGfs, files: &[DatedFile]) -> HashSet<DatedFile> {
+    let mut files = files.to_vec();
+    files.sort_by_key(|file| std::cmp::Reverse(file.time));
 
-    if config.btrfs {
-        trace!("btrfs subvolume delete {file_path:?}");
-        use std::process::Command;
-        let output = Command::new("btrfs")
-            .args(["subvolume", "delete"])
-            .arg(&file_path)
-            .output()
-            .wrap_err("failed to run 'btrfs subvolume delete'")?;
+    let mut kept = HashSet::new();
 
-        if !output.status.success() {
-            let msg = String::from_utf8(output.stderr)
-                .unwrap_or_else(|_| "Failed to capture stderr".to_string());
+    if let Some(keep_last) = gfs.keep_last {
+        for file in files.iter().take(keep_last as usize) {
+            kept.insert(file.clone());
+        }
+    }
+
+    let categories: [GfsCategory; 5] = [
+        (gfs.keep_hourly, |f| f.format("%Y%m%d%H").to_string()),
+        (gfs.keep_daily, |f| f.format("%Y%m%d").to_string()),
+        (gfs.keep_weekly, |f| {
+            let week = f.iso_week();
+            format!("{}{:02}", week.year(), week.week())
+        }),
+        (gfs.keep_monthly, |f| f.format("%Y%m").to_string()),
+        (gfs.keep_yearly, |f| f.format("%Y").to_string()),
+    ];
+
+    for (count, bucket_key) in categories {
+        let Some(count) = count else { continue };
+        if count == 0 {
+            continue;
+        }
 
-            return Err(
-                eyre!("btrfs subvolume delete exited with code {}", output.status)
+        let mut seen_buckets = HashSet::new();
+        for file in &files {
+            if seen_buckets.len() >= count as usize {
+                break;
+            }
+            if seen_buckets.insert(bucket_key(&file.time)) {
+                kept.insert(file.clone());
+            }
+        }
+    }
+
+    kept
+}
+
+fn delete_file(config: &Config, file: &DatedFile) -> eyre::Result<()> {
+    use std::process::Command;
+
+    let file_path = &file.path;
+
+    match &config.delete_method {
+        DeleteMethod::Remove => {
+            if file_path.is_dir() {
+                trace!("rm -r {file_path:?}");
+                fs::remove_dir_all(file_path)
+                    .wrap_err_with(|| format!("Failed to remove directory {file_path:?}"))?;
+            } else {
+                trace!("rm {file_path:?}");
+                fs::remove_file(file_path)
+                    .wrap_err_with(|| format!("Failed to remove file {file_path:?}"))?;
+            }
+        }
+
+        DeleteMethod::BtrfsSubvolume => {
+            trace!("btrfs subvolume delete {file_path:?}");
+            let output = Command::new("btrfs")
+                .args(["subvolume", "delete"])
+                .arg(file_path)
+                .output()
+                .wrap_err("failed to run 'btrfs subvolume delete'")?;
+
+            if !output.status.success() {
+                let msg = String::from_utf8(output.stderr)
+                    .unwrap_or_else(|_| "Failed to capture stderr".to_string());
+
+                return Err(
+                    eyre!("btrfs subvolume delete exited with code {}", output.status)
+                        .wrap_err(msg)
+                        .wrap_err(format!("Failed to delete subvolume {file_path:?}")),
+                );
+            }
+        }
+
+        DeleteMethod::MoveTo(archive_dir) => {
+            let file_name = file_path
+                .file_name()
+                .ok_or_else(|| eyre!("{file_path:?} has no file name"))?;
+            let dest = archive_dir.join(file_name);
+
+            trace!("mv {file_path:?} {dest:?}");
+            fs::create_dir_all(archive_dir)
+                .wrap_err_with(|| format!("Failed to create archive directory {archive_dir:?}"))?;
+            fs::rename(file_path, &dest)
+                .wrap_err_with(|| format!("Failed to move {file_path:?} to {dest:?}"))?;
+        }
+
+        DeleteMethod::Command(command) => {
+            let [program, args @ ..] = command.as_slice() else {
+                return Err(eyre!("delete_method.command must not be empty"));
+            };
+
+            trace!("{program} {args:?} {file_path:?}");
+            let output = Command::new(program)
+                .args(args)
+                .arg(file_path)
+                .output()
+                .wrap_err_with(|| format!("failed to run {program:?}"))?;
+
+            if !output.status.success() {
+                let msg = String::from_utf8(output.stderr)
+                    .unwrap_or_else(|_| "Failed to capture stderr".to_string());
+
+                return Err(eyre!("{program} exited with code {}", output.status)
                     .wrap_err(msg)
-                    .wrap_err(format!("Failed to delete subvolume {file_path:?}")),
-            );
-        };
-    } else if file_path.is_dir() {
-        trace!("rm -r {file_path:?}");
-        fs::remove_dir_all(&file_path)
-            .wrap_err_with(|| format!("Failed to remove directory {file_path:?}"))?;
-    } else {
-        trace!("rm {file_path:?}");
-        fs::remove_file(&file_path)
-            .wrap_err_with(|| format!("Failed to remove file {file_path:?}"))?;
+                    .wrap_err(format!("Failed to delete {file_path:?} via command")));
+            }
+        }
     }
 
     Ok(())
@@ -281,7 +798,7 @@ impl ChunkTime {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::config::{ConfPeriod, SimpleDuration};
+    use crate::config::{ConfGfs, ConfPeriod, SimpleDuration};
     use chrono::DateTime;
 
     #[test]
@@ -466,7 +983,11 @@ mod test {
             "2020-01-03T22:00:33+00:00",
             "2020-01-03T23:00:00+00:00",
         ];
-        let input = input.map(|date| DateTime::parse_from_rfc3339(date).unwrap());
+        let input = input.map(|date| DatedFile {
+            time: DateTime::parse_from_rfc3339(date).unwrap(),
+            path: PathBuf::from(date),
+            size: 0,
+        });
 
         let expected_output = [
             "2020-01-01T01:00:00+00:00",
@@ -489,12 +1010,58 @@ mod test {
         ];
         let expected_output: HashSet<_> = expected_output
             .into_iter()
-            .map(|date| DateTime::parse_from_rfc3339(date).unwrap())
+            .map(|date| DatedFile {
+                time: DateTime::parse_from_rfc3339(date).unwrap(),
+                path: PathBuf::from(date),
+                size: 0,
+            })
             .collect();
 
         let start_time = DateTime::parse_from_rfc3339("2020-01-04T00:00:00+00:00").unwrap();
 
-        let output = check_files_to_keep(start_time.into(), &periods, &input).unwrap();
+        let output = check_files_to_keep(start_time.into(), &periods, &input);
+
+        assert_eq!(output, expected_output);
+    }
+
+    #[test]
+    fn gfs_retention() {
+        let gfs = ConfGfs {
+            keep_last: Some(2),
+            keep_daily: Some(3),
+            ..Default::default()
+        };
+
+        let input = [
+            "2020-01-01T12:00:00+00:00",
+            "2020-01-02T12:00:00+00:00",
+            "2020-01-03T12:00:00+00:00",
+            "2020-01-04T12:00:00+00:00",
+            "2020-01-05T12:00:00+00:00",
+        ]
+        .map(|date| DatedFile {
+            time: DateTime::parse_from_rfc3339(date).unwrap(),
+            path: PathBuf::from(date),
+            size: 0,
+        });
+
+        // keep_last=2 keeps the 2 newest files (Jan 4 and 5); keep_daily=3
+        // keeps the newest file in each of the 3 newest distinct days (Jan
+        // 3, 4 and 5). Their union is Jan 3 through 5.
+        let expected_output: HashSet<_> = [
+            "2020-01-03T12:00:00+00:00",
+            "2020-01-04T12:00:00+00:00",
+            "2020-01-05T12:00:00+00:00",
+        ]
+        .into_iter()
+        .map(|date| DatedFile {
+            time: DateTime::parse_from_rfc3339(date).unwrap(),
+            path: PathBuf::from(date),
+            size: 0,
+        })
+        .collect();
+
+        let output = check_files_to_keep_gfs(&gfs, &input);
 
         assert_eq!(output, expected_output);
     }